@@ -0,0 +1,54 @@
+// Benchmarks end-to-end throughput of the sharded pipeline on a synthetic multi-million-row
+// input, varying `--threads` to check that sharding by `client % threads` actually buys
+// near-linear speedup instead of just adding overhead.
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    process::Command,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tempfile::NamedTempFile;
+
+const ROWS: usize = 2_000_000;
+const CLIENTS: u16 = 1_000;
+
+// Deposits only: enough to exercise the sharded dispatch path without every row
+// needing a prior transaction to reference.
+fn write_synthetic_input() -> NamedTempFile {
+    let file = NamedTempFile::new().expect("failed to create temp input file");
+    let mut writer = BufWriter::new(File::create(file.path()).expect("failed to open temp file"));
+
+    writeln!(writer, "type,client,tx,amount").unwrap();
+    for tx in 0..ROWS {
+        let client = (tx % CLIENTS as usize) as u16;
+        writeln!(writer, "deposit,{client},{tx},1.0").unwrap();
+    }
+    writer.flush().unwrap();
+
+    file
+}
+
+fn bench_threads(c: &mut Criterion) {
+    let input = write_synthetic_input();
+    let mut group = c.benchmark_group("throughput");
+
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &threads| {
+            b.iter(|| {
+                let output = Command::new(env!("CARGO_BIN_EXE_pengine"))
+                    .arg(input.path())
+                    .arg("--threads")
+                    .arg(threads.to_string())
+                    .output()
+                    .expect("failed to run pengine");
+                assert!(output.status.success());
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_threads);
+criterion_main!(benches);
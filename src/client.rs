@@ -1,7 +1,9 @@
-use anyhow::{bail, Result};
 use rust_decimal::Decimal;
 
-use crate::transaction::{ClientId, Funds, TransactionId, Transactions};
+use crate::{
+    error::LedgerError,
+    transaction::{ClientId, Funds, TransactionId, Transactions},
+};
 
 pub struct ClientAccount {
     pub client: ClientId,
@@ -25,16 +27,18 @@ impl ClientAccount {
 }
 
 // NOTE:
-// Deposit and Withdraw do not return Result, because even if they fail (if amount is None or
-// because withdrawal was attempted with insufficient funds), I assume they still have to be kept
-// in a transaction log.
+// Deposit and Withdraw still log a failed attempt via the transaction log even though they now
+// return a `LedgerError` - the caller (`ClientRepository::process`) logs the transaction before
+// propagating the error, so it's still recorded.
 //
 // I'm not 100% certain about logging withdrawal in case of insufficient funds. What if there's a
 // dispute for a failed withdrawal? It could be resolved and thus lose money. I'll keep this simple
 // for now and log it anyway.
 impl Transactions for ClientAccount {
-    fn deposit(&mut self, tx: TransactionId, amount: Option<Funds>) {
-        let Some(amount) = amount else { return }; // Deposit amount must be Some
+    fn deposit(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError> {
+        if self.locked {
+            return Err(LedgerError::FrozenAccount);
+        }
 
         self.available += amount;
         self.total += amount;
@@ -43,10 +47,14 @@ impl Transactions for ClientAccount {
             client = self.client,
             total = self.total,
             available = self.available);
+
+        Ok(())
     }
 
-    fn withdraw(&mut self, tx: TransactionId, amount: Option<Funds>) {
-        let Some(amount) = amount else { return }; // Withdrawal amount must be Some
+    fn withdraw(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError> {
+        if self.locked {
+            return Err(LedgerError::FrozenAccount);
+        }
 
         if self.available < amount {
             log::warn!("ClientId: {client} - tx: {tx} - Failed to withdraw amount: {amount:.4} - Insufficient funds - total: {total:.4}, available: {available:.4}",
@@ -54,7 +62,7 @@ impl Transactions for ClientAccount {
                 total = self.total,
                 available = self.available);
 
-            return;
+            return Err(LedgerError::NotEnoughFunds);
         }
 
         self.available -= amount;
@@ -64,12 +72,14 @@ impl Transactions for ClientAccount {
             client = self.client,
             total = self.total,
             available = self.available);
+
+        Ok(())
     }
 
-    fn dispute(&mut self, tx: TransactionId, amount: Option<Funds>) -> Result<()> {
-        let Some(amount) = amount else {
-            bail!("ClientId: {client} - tx: {tx} - Failed to dispute transaction: Disputed transaction has an unspecified amount (is not Deposit or Withdrawal)", client = self.client);
-        };
+    fn dispute(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError> {
+        if self.locked {
+            return Err(LedgerError::FrozenAccount);
+        }
 
         self.available -= amount;
         self.held += amount;
@@ -82,10 +92,10 @@ impl Transactions for ClientAccount {
         Ok(())
     }
 
-    fn resolve(&mut self, tx: TransactionId, amount: Option<Funds>) -> Result<()> {
-        let Some(amount) = amount else {
-            bail!("ClientId: {client} - tx: {tx} - Failed to resolve transaction: Resolved transaction has an unspecified amount (is not Deposit or Withdrawal)", client = self.client);
-        };
+    fn resolve(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError> {
+        if self.locked {
+            return Err(LedgerError::FrozenAccount);
+        }
 
         self.held -= amount;
         self.available += amount;
@@ -98,10 +108,10 @@ impl Transactions for ClientAccount {
         Ok(())
     }
 
-    fn chargeback(&mut self, tx: TransactionId, amount: Option<Funds>) -> Result<()> {
-        let Some(amount) = amount else {
-            bail!("ClientId: {client} - tx: {tx} - Failed to resolve transaction: Resolved transaction has an unspecified amount (is not Deposit or Withdrawal)", client = self.client);
-        };
+    fn chargeback(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError> {
+        if self.locked {
+            return Err(LedgerError::FrozenAccount);
+        }
 
         self.held -= amount;
         self.total -= amount;
@@ -0,0 +1,31 @@
+use thiserror::Error;
+
+use crate::transaction::{ClientId, TransactionId};
+
+// Structured outcomes for rejected operations. Replaces the previous mix of
+// `log::warn!` and `anyhow::bail!` so callers (currently just `main`, but
+// potentially other embedding programs) can match on what went wrong instead
+// of parsing strings.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LedgerError {
+    #[error("insufficient available funds for withdrawal")]
+    NotEnoughFunds,
+
+    #[error("client {0} has no known transaction {1}")]
+    UnknownTx(ClientId, TransactionId),
+
+    #[error("transaction is already disputed, resolved or chargedback")]
+    AlreadyDisputed,
+
+    #[error("transaction is not currently under dispute")]
+    NotDisputed,
+
+    #[error("transaction does not belong to this client")]
+    WrongOwner,
+
+    #[error("account is frozen")]
+    FrozenAccount,
+
+    #[error("first transaction for a client must be a deposit")]
+    FirstTransactionNotDeposit,
+}
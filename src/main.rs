@@ -1,27 +1,58 @@
-use std::{env, path::PathBuf};
+use std::{
+    env,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
 
-use anyhow::Context;
+use anyhow::{Context, Ok, Result};
 use csv::Trim;
+use error::LedgerError;
 use repository::ClientRepository;
-use std::{fs::File, io::BufReader};
-use transaction::Transaction;
-
-use anyhow::{Ok, Result};
+use store::{BTreeMapStore, SledStore, TransactionStore};
+use transaction::{Transaction, TransactionRecord};
 
 mod client;
+mod error;
 mod repository;
+mod store;
 mod transaction;
 
 fn main() -> Result<()> {
     env_logger::init();
 
-    // First argument only
-    let path: PathBuf = env::args()
-        .nth(1)
-        .context("Expected at least one argument")?
-        .into();
+    // First positional argument is the CSV path; `--disk-store <path>` selects a disk-backed
+    // transaction log for inputs whose reversible-transaction history exceeds RAM, and
+    // `--threads <n>` shards client processing across worker threads.
+    let mut csv_path: Option<PathBuf> = None;
+    let mut disk_store_path: Option<PathBuf> = None;
+    let mut threads: usize = 1;
+    let mut args = env::args().skip(1);
 
-    let mut client_repository = ClientRepository::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--disk-store" => {
+                disk_store_path = Some(
+                    args.next()
+                        .context("--disk-store requires a path argument")?
+                        .into(),
+                );
+            }
+            "--threads" => {
+                threads = args
+                    .next()
+                    .context("--threads requires a number")?
+                    .parse()
+                    .context("--threads must be a positive integer")?;
+            }
+            _ => csv_path = Some(arg.into()),
+        }
+    }
+
+    let path = csv_path.context("Expected at least one argument")?;
+    let threads = threads.max(1);
 
     // Set up csv reader
     let file = File::open(path)?;
@@ -32,15 +63,86 @@ fn main() -> Result<()> {
         .trim(Trim::All)
         .from_reader(reader);
 
-    // Read line by line and process
+    // Transactions for distinct clients are fully independent, so each client is pinned (by
+    // `client % threads`) to one shard. A shard owns its own `ClientRepository` - accounts and
+    // transaction log - behind its own channel, so no locking is needed on the hot path.
+    let (senders, workers): (Vec<_>, Vec<_>) = (0..threads)
+        .map(|shard| {
+            let (tx, rx) = mpsc::channel::<Transaction>();
+
+            let store: Box<dyn TransactionStore> = match &disk_store_path {
+                Some(path) => Box::new(SledStore::open(&shard_store_path(path, shard))?),
+                None => Box::new(BTreeMapStore::default()),
+            };
+
+            let handle = thread::spawn(move || -> ClientRepository {
+                let mut repository = ClientRepository::with_store(store);
+
+                for transaction in rx {
+                    if let Err(e) = repository.process(transaction) {
+                        warn_rejected_transaction(e);
+                    }
+                }
+
+                repository
+            });
+
+            Ok((tx, handle))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+
+    // Read line by line, validate the record's amount against its type, and dispatch to the
+    // owning shard
     for line in csv_reader.deserialize() {
-        let transaction: Transaction = line?;
-        if let Err(e) = client_repository.process(transaction) {
-            log::error!("{e}");
-        }
+        let record: TransactionRecord = line?;
+        let transaction = match Transaction::try_from(record) {
+            Result::Ok(transaction) => transaction,
+            Err(e) => {
+                log::warn!("Skipping malformed record: {e}");
+                continue;
+            }
+        };
+
+        let shard = transaction.client() as usize % threads;
+        // A worker only disconnects if it panicked; nothing sensible to do but drop the rest
+        // of its work on the floor.
+        let _ = senders[shard].send(transaction);
+    }
+    drop(senders);
+
+    // Shards partition clients disjointly, so merging is just a union - no client id can appear
+    // in more than one shard's map, and `BTreeMap` keeps the merged output in sorted client order.
+    let mut client_repository = ClientRepository::new();
+    for worker in workers {
+        let shard_repository = worker.join().expect("worker thread panicked");
+        client_repository.clients.extend(shard_repository.clients);
     }
 
     client_repository.output()?;
 
     Ok(())
 }
+
+fn shard_store_path(base: &Path, shard: usize) -> PathBuf {
+    let mut file_name = base.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!("-shard{shard}"));
+    base.with_file_name(file_name)
+}
+
+// Every current LedgerError variant is a rejection of malformed/adversarial input, not a reason
+// to stop processing the rest of the file - so they're all skippable warnings. This match is
+// kept exhaustive so a future variant forces a conscious decision here instead of silently
+// falling into the same bucket.
+fn warn_rejected_transaction(e: LedgerError) {
+    match e {
+        LedgerError::NotEnoughFunds
+        | LedgerError::UnknownTx(_, _)
+        | LedgerError::AlreadyDisputed
+        | LedgerError::NotDisputed
+        | LedgerError::WrongOwner
+        | LedgerError::FrozenAccount
+        | LedgerError::FirstTransactionNotDeposit => log::warn!("Skipping transaction: {e}"),
+    }
+}
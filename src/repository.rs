@@ -7,24 +7,46 @@ use anyhow::Result;
 
 use crate::{
     client::ClientAccount,
-    transaction::{
-        ClientId, CsvOutput, Funds, Transaction, TransactionId, TransactionType, Transactions,
-    },
+    error::LedgerError,
+    store::{BTreeMapStore, LoggedTransaction, TransactionStore},
+    transaction::{ClientId, CsvOutput, Funds, Transaction, TransactionId, Transactions, TxState},
 };
 
 pub struct ClientRepository {
     pub clients: BTreeMap<ClientId, ClientAccount>,
-    pub transaction_log: BTreeMap<TransactionId, Transaction>,
+    store: Box<dyn TransactionStore>,
 }
 
 impl ClientRepository {
     pub fn new() -> Self {
+        Self::with_store(Box::new(BTreeMapStore::default()))
+    }
+
+    pub fn with_store(store: Box<dyn TransactionStore>) -> Self {
         Self {
             clients: BTreeMap::new(),
-            transaction_log: BTreeMap::new(),
+            store,
+        }
+    }
+
+    // Check the current state of `tx` against `from` and, if it matches,
+    // atomically transition it to `to`. Returns false (no mutation) if the
+    // transaction is unknown or not in the expected state.
+    fn transition_tx_state(&mut self, tx: TransactionId, from: TxState, to: TxState) -> bool {
+        match self.store.get(tx) {
+            Some(record) if record.state == from => {
+                self.store.update_state(tx, to);
+                true
+            }
+            _ => false,
         }
     }
 
+    #[cfg(test)]
+    fn tx_state(&self, tx: TransactionId) -> Option<TxState> {
+        self.store.get(tx).map(|record| record.state)
+    }
+
     pub fn output(self) -> Result<()> {
         let mut writer = csv::Writer::from_writer(io::stdout());
 
@@ -41,144 +63,164 @@ impl ClientRepository {
         Ok(())
     }
 
-    pub fn process(&mut self, input: Transaction) -> Result<()> {
-        let Transaction {
-            typ,
-            client,
-            tx,
-            amount,
-        } = input;
-
-        match self.clients.entry(client) {
-            Entry::Occupied(mut o) => match typ {
-                TransactionType::Deposit => {
-                    o.get_mut().deposit(tx, amount);
-                    self.log_transaction(typ, client, tx, amount);
+    pub fn process(&mut self, input: Transaction) -> Result<(), LedgerError> {
+        let client = input.client();
+        let tx = input.tx();
+
+        // A chargedback account is frozen: no further deposit, withdrawal, dispute, resolve or
+        // chargeback may touch it.
+        if self.clients.get(&client).is_some_and(|account| account.locked) {
+            return Err(LedgerError::FrozenAccount);
+        }
+
+        match input {
+            Transaction::Deposit { amount, .. } => match self.clients.entry(client) {
+                Entry::Occupied(mut o) => {
+                    let result = o.get_mut().deposit(tx, amount);
+                    self.log_transaction(client, tx, amount);
+                    result
                 }
-                TransactionType::Withdrawal => {
-                    o.get_mut().withdraw(tx, amount);
-                    self.log_transaction(typ, client, tx, amount);
+                // Client not previously present. Create account first - I assume there is no
+                // point in trying to process a withdrawal/dispute/resolve/chargeback if there
+                // was no account present in the first place, so I choose to only create an
+                // account on first deposit.
+                Entry::Vacant(v) => {
+                    let mut client_account = ClientAccount::new(client);
+                    let result = client_account.deposit(tx, amount);
+                    v.insert(client_account);
+                    self.log_transaction(client, tx, amount);
+                    result
                 }
-                TransactionType::Dispute => {
-                    if let Some(transaction) = self.transaction_log.get_mut(&tx) {
-                        // Only process transaction if it actually belonged to the client
-                        if transaction.client == client {
-                            o.get_mut().dispute(tx, transaction.amount)?;
-                            transaction.typ = TransactionType::Dispute;
-                        }
-                    }
+            },
+            Transaction::Withdrawal { amount, .. } => match self.clients.get_mut(&client) {
+                Some(account) => {
+                    let result = account.withdraw(tx, amount);
+                    self.log_transaction(client, tx, amount);
+                    result
+                }
+                None => Err(LedgerError::FirstTransactionNotDeposit),
+            },
+            Transaction::Dispute { .. } => {
+                if !self.clients.contains_key(&client) {
+                    return Err(LedgerError::FirstTransactionNotDeposit);
                 }
-                TransactionType::Resolve => {
-                    if let Some(transaction) = self.transaction_log.get(&tx) {
-                        // Only process transaction if its was disputed and it actually belonged to the client
-                        if matches!(transaction.typ, TransactionType::Dispute)
-                            && transaction.client == client
-                        {
-                            o.get_mut().resolve(tx, transaction.amount)?;
-                        } else {
-                            log::warn!("Skipping {typ:?} for client: {client}. Transaction for Resolve is not under Dispute or belongs to the wrong client");
-                        }
+
+                // Look up and transition the tx's state before re-acquiring the account, so the
+                // `self.store` borrow above never overlaps with the `self.clients` borrow below.
+                match self.store.get(tx).map(|record| (record.client, record.amount)) {
+                    None => Err(LedgerError::UnknownTx(client, tx)),
+                    Some((owner, _)) if owner != client => Err(LedgerError::WrongOwner),
+                    Some((_, amount))
+                        if self.transition_tx_state(tx, TxState::Processed, TxState::Disputed) =>
+                    {
+                        self.clients.get_mut(&client).unwrap().dispute(tx, amount)
                     }
+                    Some(_) => Err(LedgerError::AlreadyDisputed),
+                }
+            }
+            Transaction::Resolve { .. } => {
+                if !self.clients.contains_key(&client) {
+                    return Err(LedgerError::FirstTransactionNotDeposit);
                 }
-                TransactionType::Chargeback => {
-                    if let Some(transaction) = self.transaction_log.get(&tx) {
-                        // Only process transaction if its was disputed and it actually belonged to the client
-                        if matches!(transaction.typ, TransactionType::Dispute)
-                            && transaction.client == client
-                        {
-                            o.get_mut().chargeback(tx, transaction.amount)?;
-                        } else {
-                            log::warn!("Skipping {typ:?} for client: {client}. Transaction for Resolve is not under Dispute or belongs to the wrong client");
-                        }
+
+                match self.store.get(tx).map(|record| (record.client, record.amount)) {
+                    None => Err(LedgerError::UnknownTx(client, tx)),
+                    Some((owner, _)) if owner != client => Err(LedgerError::WrongOwner),
+                    Some((_, amount))
+                        if self.transition_tx_state(tx, TxState::Disputed, TxState::Resolved) =>
+                    {
+                        self.clients.get_mut(&client).unwrap().resolve(tx, amount)
                     }
+                    Some(_) => Err(LedgerError::NotDisputed),
                 }
-            },
-            // Client not previously present. Create account first - I assume there is no point in
-            // trying to process a withdrawal/dispute/resolve/chargeback if there was no account
-            // present in the first place, so I choose to only create an account on first deposit.
-            Entry::Vacant(v) => match typ {
-                TransactionType::Deposit => {
-                    let mut client = ClientAccount::new(client);
-                    client.deposit(tx, amount);
-                    v.insert(client);
+            }
+            Transaction::Chargeback { .. } => {
+                if !self.clients.contains_key(&client) {
+                    return Err(LedgerError::FirstTransactionNotDeposit);
                 }
-                _ => log::warn!(
-                    "Skipping {typ:?} for client: {client}. First transaction must be a Deposit."
-                ),
-            },
-        }
 
-        Ok(())
+                match self.store.get(tx).map(|record| (record.client, record.amount)) {
+                    None => Err(LedgerError::UnknownTx(client, tx)),
+                    Some((owner, _)) if owner != client => Err(LedgerError::WrongOwner),
+                    Some((_, amount))
+                        if self.transition_tx_state(
+                            tx,
+                            TxState::Disputed,
+                            TxState::ChargedBack,
+                        ) =>
+                    {
+                        self.clients.get_mut(&client).unwrap().chargeback(tx, amount)
+                    }
+                    Some(_) => Err(LedgerError::NotDisputed),
+                }
+            }
+        }
     }
 
     // Keep transaction log for Dispute/Resolve/Chargeback lookup purposes
-    fn log_transaction(
-        &mut self,
-        typ: TransactionType,
-        client: ClientId,
-        tx: TransactionId,
-        amount: Option<Funds>,
-    ) {
-        let transaction = Transaction {
-            typ,
-            client,
+    fn log_transaction(&mut self, client: ClientId, tx: TransactionId, amount: Funds) {
+        self.store.insert(
             tx,
-            amount,
-        };
-        self.transaction_log.insert(tx, transaction);
+            LoggedTransaction {
+                client,
+                amount,
+                state: TxState::Processed,
+            },
+        );
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use rust_decimal_macros::dec;
 
     #[test]
     fn deposit() {
         let mut client = ClientAccount {
             client: 1,
-            available: 0.0,
-            held: 0.0,
-            total: 0.0,
+            available: dec!(0.0),
+            held: dec!(0.0),
+            total: dec!(0.0),
             locked: false,
         };
 
-        client.deposit(1, Some(10.0));
+        let _ = client.deposit(1, dec!(10.0));
 
-        assert_eq!(client.available, 10.0);
-        assert_eq!(client.total, 10.0);
+        assert_eq!(client.available, dec!(10.0));
+        assert_eq!(client.total, dec!(10.0));
     }
 
     #[test]
     fn withdraw() {
         let mut client = ClientAccount {
             client: 1,
-            available: 10.0,
-            held: 0.0,
-            total: 10.0,
+            available: dec!(10.0),
+            held: dec!(0.0),
+            total: dec!(10.0),
             locked: false,
         };
 
-        client.withdraw(1, Some(5.0));
+        let _ = client.withdraw(1, dec!(5.0));
 
-        assert_eq!(client.available, 5.0);
-        assert_eq!(client.total, 5.0);
+        assert_eq!(client.available, dec!(5.0));
+        assert_eq!(client.total, dec!(5.0));
     }
 
     #[test]
     fn withdraw_insufficient_funds() {
         let mut client = ClientAccount {
             client: 1,
-            available: 1.0,
-            held: 0.0,
-            total: 1.0,
+            available: dec!(1.0),
+            held: dec!(0.0),
+            total: dec!(1.0),
             locked: false,
         };
 
-        client.withdraw(1, Some(5.0));
+        let _ = client.withdraw(1, dec!(5.0));
 
-        assert_eq!(client.available, 1.0);
-        assert_eq!(client.total, 1.0);
+        assert_eq!(client.available, dec!(1.0));
+        assert_eq!(client.total, dec!(1.0));
     }
 
     #[test]
@@ -186,24 +228,17 @@ mod test {
         let mut repo = ClientRepository::new();
 
         let transactions = vec![
-            Transaction {
-                typ: TransactionType::Deposit,
+            Transaction::Deposit {
                 client: 1,
                 tx: 1,
-                amount: Some(10.0),
+                amount: dec!(10.0),
             },
-            Transaction {
-                typ: TransactionType::Withdrawal,
+            Transaction::Withdrawal {
                 client: 1,
                 tx: 2,
-                amount: Some(3.0),
-            },
-            Transaction {
-                typ: TransactionType::Dispute,
-                client: 1,
-                tx: 2,
-                amount: None,
+                amount: dec!(3.0),
             },
+            Transaction::Dispute { client: 1, tx: 2 },
         ];
 
         for transaction in transactions {
@@ -212,9 +247,9 @@ mod test {
 
         let client = repo.clients.get(&1).unwrap();
 
-        assert_eq!(client.total, 7.0);
-        assert_eq!(client.available, 4.0);
-        assert_eq!(client.held, 3.0);
+        assert_eq!(client.total, dec!(7.0));
+        assert_eq!(client.available, dec!(4.0));
+        assert_eq!(client.held, dec!(3.0));
     }
 
     #[test]
@@ -222,30 +257,48 @@ mod test {
         let mut repo = ClientRepository::new();
 
         let transactions = vec![
-            Transaction {
-                typ: TransactionType::Deposit,
+            Transaction::Deposit {
                 client: 1,
                 tx: 1,
-                amount: Some(10.0),
+                amount: dec!(10.0),
             },
-            Transaction {
-                typ: TransactionType::Withdrawal,
+            Transaction::Withdrawal {
                 client: 1,
                 tx: 2,
-                amount: Some(3.0),
+                amount: dec!(3.0),
             },
-            Transaction {
-                typ: TransactionType::Dispute,
+            Transaction::Dispute { client: 1, tx: 2 },
+            Transaction::Resolve { client: 1, tx: 2 },
+        ];
+
+        for transaction in transactions {
+            let _ = repo.process(transaction);
+        }
+
+        let client = repo.clients.get(&1).unwrap();
+
+        assert_eq!(client.total, dec!(7.0));
+        assert_eq!(client.available, dec!(7.0));
+        assert_eq!(client.held, dec!(0.0));
+    }
+
+    #[test]
+    fn chargeback_withdrawal() {
+        let mut repo = ClientRepository::new();
+
+        let transactions = vec![
+            Transaction::Deposit {
                 client: 1,
-                tx: 2,
-                amount: None,
+                tx: 1,
+                amount: dec!(10.0),
             },
-            Transaction {
-                typ: TransactionType::Resolve,
+            Transaction::Withdrawal {
                 client: 1,
                 tx: 2,
-                amount: None,
+                amount: dec!(3.0),
             },
+            Transaction::Dispute { client: 1, tx: 2 },
+            Transaction::Chargeback { client: 1, tx: 2 },
         ];
 
         for transaction in transactions {
@@ -254,39 +307,89 @@ mod test {
 
         let client = repo.clients.get(&1).unwrap();
 
-        assert_eq!(client.total, 7.0);
-        assert_eq!(client.available, 7.0);
-        assert_eq!(client.held, 0.0);
+        assert_eq!(client.total, dec!(4.0));
+        assert_eq!(client.available, dec!(4.0));
+        assert_eq!(client.held, dec!(0.0));
+        assert!(client.locked);
     }
 
     #[test]
-    fn chargeback_withdrawal() {
+    fn double_dispute_is_rejected() {
+        let mut repo = ClientRepository::new();
+
+        let transactions = vec![
+            Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: dec!(10.0),
+            },
+            Transaction::Dispute { client: 1, tx: 1 },
+            Transaction::Dispute { client: 1, tx: 1 },
+        ];
+
+        for transaction in transactions {
+            let _ = repo.process(transaction);
+        }
+
+        let client = repo.clients.get(&1).unwrap();
+
+        // Second dispute must be a no-op: only held once, not twice
+        assert_eq!(client.total, dec!(10.0));
+        assert_eq!(client.available, dec!(0.0));
+        assert_eq!(client.held, dec!(10.0));
+        assert_eq!(repo.tx_state(1), Some(TxState::Disputed));
+    }
+
+    #[test]
+    fn resolve_after_chargeback_is_rejected() {
         let mut repo = ClientRepository::new();
 
         let transactions = vec![
-            Transaction {
-                typ: TransactionType::Deposit,
+            Transaction::Deposit {
                 client: 1,
                 tx: 1,
-                amount: Some(10.0),
+                amount: dec!(10.0),
             },
-            Transaction {
-                typ: TransactionType::Withdrawal,
+            Transaction::Dispute { client: 1, tx: 1 },
+            Transaction::Chargeback { client: 1, tx: 1 },
+            Transaction::Resolve { client: 1, tx: 1 },
+        ];
+
+        for transaction in transactions {
+            let _ = repo.process(transaction);
+        }
+
+        let client = repo.clients.get(&1).unwrap();
+
+        // Resolve after chargeback must not restore funds
+        assert_eq!(client.total, dec!(0.0));
+        assert_eq!(client.available, dec!(0.0));
+        assert_eq!(client.held, dec!(0.0));
+        assert!(client.locked);
+        assert_eq!(repo.tx_state(1), Some(TxState::ChargedBack));
+    }
+
+    #[test]
+    fn deposit_after_chargeback_is_rejected() {
+        let mut repo = ClientRepository::new();
+
+        let transactions = vec![
+            Transaction::Deposit {
                 client: 1,
-                tx: 2,
-                amount: Some(3.0),
+                tx: 1,
+                amount: dec!(10.0),
             },
-            Transaction {
-                typ: TransactionType::Dispute,
+            Transaction::Dispute { client: 1, tx: 1 },
+            Transaction::Chargeback { client: 1, tx: 1 },
+            Transaction::Deposit {
                 client: 1,
                 tx: 2,
-                amount: None,
+                amount: dec!(50.0),
             },
-            Transaction {
-                typ: TransactionType::Chargeback,
+            Transaction::Withdrawal {
                 client: 1,
-                tx: 2,
-                amount: None,
+                tx: 3,
+                amount: dec!(1.0),
             },
         ];
 
@@ -296,9 +399,11 @@ mod test {
 
         let client = repo.clients.get(&1).unwrap();
 
-        assert_eq!(client.total, 4.0);
-        assert_eq!(client.available, 4.0);
-        assert_eq!(client.held, 0.0);
+        // Account is frozen after chargeback: neither the later deposit nor withdrawal may
+        // touch its balances.
+        assert_eq!(client.total, dec!(0.0));
+        assert_eq!(client.available, dec!(0.0));
+        assert_eq!(client.held, dec!(0.0));
         assert!(client.locked);
     }
 }
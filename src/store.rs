@@ -0,0 +1,98 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::transaction::{ClientId, Funds, TransactionId, TxState};
+
+// What must be recalled for a disputable Deposit/Withdrawal: its owner, its amount, and its
+// current dispute lifecycle state.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoggedTransaction {
+    pub client: ClientId,
+    pub amount: Funds,
+    pub state: TxState,
+}
+
+// Swappable backing store for the transaction log. `BTreeMapStore` (the default) keeps
+// everything in memory; `SledStore` spills to disk so inputs whose reversible-transaction
+// history exceeds RAM can still be processed. `Send` is required so a store can be handed off
+// to a worker thread when client processing is sharded.
+pub trait TransactionStore: Send {
+    fn insert(&mut self, tx: TransactionId, record: LoggedTransaction);
+    fn get(&self, tx: TransactionId) -> Option<LoggedTransaction>;
+    fn update_state(&mut self, tx: TransactionId, state: TxState);
+}
+
+#[derive(Default)]
+pub struct BTreeMapStore {
+    log: BTreeMap<TransactionId, LoggedTransaction>,
+}
+
+impl TransactionStore for BTreeMapStore {
+    fn insert(&mut self, tx: TransactionId, record: LoggedTransaction) {
+        self.log.insert(tx, record);
+    }
+
+    fn get(&self, tx: TransactionId) -> Option<LoggedTransaction> {
+        self.log.get(&tx).copied()
+    }
+
+    fn update_state(&mut self, tx: TransactionId, state: TxState) {
+        if let Some(record) = self.log.get_mut(&tx) {
+            record.state = state;
+        }
+    }
+}
+
+// Disk-backed store built on an embedded key-value store, for CSV inputs whose reversible-
+// transaction history doesn't fit in memory.
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    // Each invocation of the engine processes one CSV input from scratch, so a store opened at
+    // `path` must start empty - otherwise a reused path would resurrect the previous run's
+    // transactions (making stale deposits disputable again and risking tx id collisions with
+    // old records). Clear out anything already at `path` rather than trusting the caller to
+    // pass an empty directory.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let db = sled::open(path)?;
+        db.clear()?;
+
+        Ok(Self { db })
+    }
+
+    fn key(tx: TransactionId) -> [u8; 4] {
+        tx.to_be_bytes()
+    }
+}
+
+impl TransactionStore for SledStore {
+    fn insert(&mut self, tx: TransactionId, record: LoggedTransaction) {
+        let bytes = match bincode::serialize(&record) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::warn!("Failed to serialize transaction {tx} for disk store: {e}");
+                return;
+            }
+        };
+
+        if let Err(e) = self.db.insert(Self::key(tx), bytes) {
+            log::warn!("Failed to write transaction {tx} to disk store: {e}");
+        }
+    }
+
+    fn get(&self, tx: TransactionId) -> Option<LoggedTransaction> {
+        let bytes = self.db.get(Self::key(tx)).ok().flatten()?;
+        bincode::deserialize(&bytes).ok()
+    }
+
+    fn update_state(&mut self, tx: TransactionId, state: TxState) {
+        let Some(mut record) = self.get(tx) else {
+            return;
+        };
+        record.state = state;
+        self.insert(tx, record);
+    }
+}
@@ -1,16 +1,21 @@
-use anyhow::Result;
+use anyhow::{bail, Context};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize, Serializer};
 
+use crate::error::LedgerError;
+
 pub type ClientId = u16;
 pub type TransactionId = u32;
-pub type Funds = f32;
+// Fixed-point representation for money: avoids the rounding drift that
+// accumulates when f32/f64 are used for arithmetic across many transactions.
+pub type Funds = Decimal;
 
 pub trait Transactions {
-    fn deposit(&mut self, tx: TransactionId, amount: Option<Funds>);
-    fn withdraw(&mut self, tx: TransactionId, amount: Option<Funds>);
-    fn dispute(&mut self, tx: TransactionId, amount: Option<Funds>) -> Result<()>;
-    fn resolve(&mut self, tx: TransactionId, amount: Option<Funds>) -> Result<()>;
-    fn chargeback(&mut self, tx: TransactionId, amount: Option<Funds>) -> Result<()>;
+    fn deposit(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError>;
+    fn withdraw(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError>;
+    fn dispute(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError>;
+    fn resolve(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError>;
+    fn chargeback(&mut self, tx: TransactionId, amount: Funds) -> Result<(), LedgerError>;
 }
 
 #[derive(Debug, Eq, PartialEq, Deserialize, Copy, Clone)]
@@ -23,9 +28,23 @@ pub enum TransactionType {
     Chargeback,
 }
 
+// Tracks the dispute lifecycle of a logged Deposit/Withdrawal, independent of
+// its original `TransactionType`. Only `Processed -> Disputed`,
+// `Disputed -> Resolved` and `Disputed -> ChargedBack` are valid transitions.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum TxState {
+    Processed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
+
+// Raw CSV row shape. Kept separate from `Transaction` so serde only has to deal with the flat,
+// partially-optional representation that actually appears on disk; `TryFrom` below is where that
+// gets validated into something the rest of the engine can trust.
 #[derive(Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "lowercase")]
-pub struct Transaction {
+pub struct TransactionRecord {
     #[serde(rename = "type")]
     pub typ: TransactionType,
     pub client: ClientId,
@@ -34,6 +53,100 @@ pub struct Transaction {
     pub amount: Option<Funds>,
 }
 
+// A validated transaction: deposit/withdrawal always carry an amount, dispute/resolve/chargeback
+// never do. Downstream processing can rely on this instead of re-checking `Option<Funds>`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Transaction {
+    Deposit {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Funds,
+    },
+    Withdrawal {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Funds,
+    },
+    Dispute {
+        client: ClientId,
+        tx: TransactionId,
+    },
+    Resolve {
+        client: ClientId,
+        tx: TransactionId,
+    },
+    Chargeback {
+        client: ClientId,
+        tx: TransactionId,
+    },
+}
+
+impl Transaction {
+    pub fn client(&self) -> ClientId {
+        match *self {
+            Transaction::Deposit { client, .. }
+            | Transaction::Withdrawal { client, .. }
+            | Transaction::Dispute { client, .. }
+            | Transaction::Resolve { client, .. }
+            | Transaction::Chargeback { client, .. } => client,
+        }
+    }
+
+    pub fn tx(&self) -> TransactionId {
+        match *self {
+            Transaction::Deposit { tx, .. }
+            | Transaction::Withdrawal { tx, .. }
+            | Transaction::Dispute { tx, .. }
+            | Transaction::Resolve { tx, .. }
+            | Transaction::Chargeback { tx, .. } => tx,
+        }
+    }
+}
+
+impl TryFrom<TransactionRecord> for Transaction {
+    type Error = anyhow::Error;
+
+    fn try_from(record: TransactionRecord) -> anyhow::Result<Self> {
+        let TransactionRecord {
+            typ,
+            client,
+            tx,
+            amount,
+        } = record;
+
+        Ok(match typ {
+            TransactionType::Deposit => Transaction::Deposit {
+                client,
+                tx,
+                amount: amount.context("deposit transaction must have an amount")?,
+            },
+            TransactionType::Withdrawal => Transaction::Withdrawal {
+                client,
+                tx,
+                amount: amount.context("withdrawal transaction must have an amount")?,
+            },
+            TransactionType::Dispute => {
+                if amount.is_some() {
+                    bail!("dispute transaction must not have an amount");
+                }
+                Transaction::Dispute { client, tx }
+            }
+            TransactionType::Resolve => {
+                if amount.is_some() {
+                    bail!("resolve transaction must not have an amount");
+                }
+                Transaction::Resolve { client, tx }
+            }
+            TransactionType::Chargeback => {
+                if amount.is_some() {
+                    bail!("chargeback transaction must not have an amount");
+                }
+                Transaction::Chargeback { client, tx }
+            }
+        })
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct CsvOutput {
@@ -48,9 +161,9 @@ pub struct CsvOutput {
 }
 
 // Output Funds with 4 decimal point precision
-fn round_serialize<S>(x: &f32, s: S) -> Result<S::Ok, S::Error>
+fn round_serialize<S>(x: &Funds, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    s.serialize_str(&format!("{x:.4}"))
+    s.serialize_str(&format!("{:.4}", x.round_dp(4)))
 }